@@ -1,6 +1,14 @@
 //! Macros for use with Madsim
+//!
+//! The `fail-artifacts` and `parallel-seeds` cargo features referenced by `#[madsim::test]`
+//! gate functionality that depends on things outside this crate: `fail-artifacts` needs
+//! `madsim`'s own `export` module (re-exporting `serde` + `serde_json`) and a rand log type
+//! that implements `Serialize`/`DeserializeOwned`, and both features need a declaration in
+//! this crate's `Cargo.toml`. Neither is present in this tree yet, so until that companion
+//! wiring lands, these features are not merely opt-in -- they're unbuildable if turned on.
 
 use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
 use quote::quote;
 
 #[allow(clippy::needless_doctest_main)]
@@ -31,7 +39,12 @@ pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
     let input = syn::parse_macro_input!(item as syn::ItemFn);
     let args = syn::parse_macro_input!(args as syn::AttributeArgs);
 
-    parse(input, args, false).unwrap_or_else(|e| e.to_compile_error().into())
+    let config = match Configuration::parse_knobs(args) {
+        Ok(config) => config,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    parse(input, config, false).unwrap_or_else(|e| e.to_compile_error().into())
 }
 
 /// Marks async function to be executed by runtime, suitable to test environment.
@@ -43,17 +56,186 @@ pub fn main(args: TokenStream, item: TokenStream) -> TokenStream {
 ///     assert!(true);
 /// }
 /// ```
+///
+/// `MADSIM_TEST_JOBS` (or `jobs = ...`) fans seed exploration out across that many OS
+/// threads, each driving its own [Runtime](../madsim/struct.Runtime.html). That only
+/// happens when madsim-macros' `parallel-seeds` feature is enabled -- enabling it is an
+/// acknowledgement that the `Runtime` in use supports being constructed and driven
+/// concurrently from multiple threads, which this crate has no way to check on its own.
+/// Without the feature the sweep stays sequential regardless of the requested job count.
+///
+/// When a run panics, set `MADSIM_TEST_ARTIFACT_DIR` to have the failing seed and its
+/// `rand_log` written to `<dir>/madsim-seed-<seed>.json` (nothing is written when the
+/// runtime returns no rand log for that run); set `MADSIM_TEST_REPLAY=<file>`
+/// to rerun exactly that seed/log once, deterministically, instead of sweeping seeds.
+/// This is only compiled in when madsim-macros' `fail-artifacts` feature is enabled, since
+/// it requires [Runtime](../madsim/struct.Runtime.html)'s rand log type to implement
+/// `Serialize` + `DeserializeOwned`, and `madsim`'s `export` module to re-export `serde`
+/// and `serde_json` for the generated code to route through; crates that don't opt into
+/// the feature never pull in that `serde`/`serde_json` requirement.
 #[proc_macro_attribute]
 pub fn test(args: TokenStream, item: TokenStream) -> TokenStream {
     let input = syn::parse_macro_input!(item as syn::ItemFn);
     let args = syn::parse_macro_input!(args as syn::AttributeArgs);
 
-    parse(input, args, true).unwrap_or_else(|e| e.to_compile_error().into())
+    let config = match Configuration::parse_knobs(args) {
+        Ok(config) => config,
+        Err(e) => return e.to_compile_error().into(),
+    };
+
+    parse(input, config, true).unwrap_or_else(|e| e.to_compile_error().into())
+}
+
+/// Configuration parsed from the `#[madsim::main]` / `#[madsim::test]` attribute
+/// arguments, e.g. `#[madsim::test(seed = 1, repeat = 10, time_limit = 30.0, check)]`.
+///
+/// `seed`, `repeat`, `time_limit` and `jobs` each set the default for the corresponding
+/// `MADSIM_TEST_*` env var, which still wins at runtime so CI sweeps that set the env
+/// vars keep working unchanged. `check` is combined with `MADSIM_TEST_CHECK_DETERMINISTIC`:
+/// either one turns the determinism check on. `crate` and `should_panic` have no env var.
+struct Configuration {
+    crate_name: syn::Path,
+    seed: Option<u64>,
+    repeat: Option<u64>,
+    time_limit: Option<f64>,
+    check: bool,
+    jobs: Option<u64>,
+    should_panic: bool,
+    expected_panic_message: Option<String>,
+}
+
+impl Default for Configuration {
+    fn default() -> Self {
+        Configuration {
+            crate_name: syn::parse_quote!(madsim),
+            seed: None,
+            repeat: None,
+            time_limit: None,
+            check: false,
+            jobs: None,
+            should_panic: false,
+            expected_panic_message: None,
+        }
+    }
+}
+
+impl Configuration {
+    fn parse_knobs(args: syn::AttributeArgs) -> Result<Self, syn::Error> {
+        let mut config = Configuration::default();
+
+        for arg in args {
+            match arg {
+                syn::NestedMeta::Meta(syn::Meta::NameValue(namevalue)) => {
+                    let ident = namevalue
+                        .path
+                        .get_ident()
+                        .ok_or_else(|| {
+                            syn::Error::new_spanned(&namevalue, "must have specified ident")
+                        })?
+                        .to_string()
+                        .to_lowercase();
+                    match ident.as_str() {
+                        "seed" => {
+                            config.seed = Some(parse_int(&namevalue.lit, "seed")?);
+                        }
+                        "repeat" => {
+                            config.repeat = Some(parse_int(&namevalue.lit, "repeat")?);
+                        }
+                        "time_limit" => {
+                            config.time_limit = Some(parse_float(&namevalue.lit, "time_limit")?);
+                        }
+                        "crate" => {
+                            config.crate_name = parse_path(&namevalue.lit, "crate")?;
+                        }
+                        "jobs" => {
+                            config.jobs = Some(parse_int(&namevalue.lit, "jobs")?);
+                        }
+                        "should_panic" => {
+                            config.should_panic = true;
+                            config.expected_panic_message =
+                                Some(parse_str(&namevalue.lit, "should_panic")?);
+                        }
+                        name => {
+                            let msg = format!(
+                                "unknown attribute {} is specified; expected one of: `seed`, `repeat`, `time_limit`, `check`, `crate`, `jobs`, `should_panic`",
+                                name,
+                            );
+                            return Err(syn::Error::new_spanned(namevalue, msg));
+                        }
+                    }
+                }
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) => {
+                    let name = path
+                        .get_ident()
+                        .ok_or_else(|| syn::Error::new_spanned(&path, "must have specified ident"))?
+                        .to_string()
+                        .to_lowercase();
+                    match name.as_str() {
+                        "check" => config.check = true,
+                        "should_panic" => config.should_panic = true,
+                        name => {
+                            let msg = format!(
+                                "unknown attribute {} is specified; expected one of: `seed`, `repeat`, `time_limit`, `check`, `crate`, `jobs`, `should_panic`",
+                                name,
+                            );
+                            return Err(syn::Error::new_spanned(path, msg));
+                        }
+                    }
+                }
+                other => {
+                    return Err(syn::Error::new_spanned(other, "unknown attribute inside the macro"));
+                }
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse_int(lit: &syn::Lit, field: &str) -> Result<u64, syn::Error> {
+    match lit {
+        syn::Lit::Int(lit) => lit.base10_parse::<u64>(),
+        _ => Err(syn::Error::new_spanned(
+            lit,
+            format!("`{}` should be an integer literal", field),
+        )),
+    }
+}
+
+fn parse_float(lit: &syn::Lit, field: &str) -> Result<f64, syn::Error> {
+    match lit {
+        syn::Lit::Float(lit) => lit.base10_parse::<f64>(),
+        syn::Lit::Int(lit) => lit.base10_parse::<u64>().map(|i| i as f64),
+        _ => Err(syn::Error::new_spanned(
+            lit,
+            format!("`{}` should be a number literal", field),
+        )),
+    }
+}
+
+fn parse_str(lit: &syn::Lit, field: &str) -> Result<String, syn::Error> {
+    match lit {
+        syn::Lit::Str(s) => Ok(s.value()),
+        _ => Err(syn::Error::new_spanned(
+            lit,
+            format!("`{}` should be a string literal", field),
+        )),
+    }
+}
+
+fn parse_path(lit: &syn::Lit, field: &str) -> Result<syn::Path, syn::Error> {
+    match lit {
+        syn::Lit::Str(s) => s.parse(),
+        _ => Err(syn::Error::new_spanned(
+            lit,
+            format!("`{}` should be a string literal", field),
+        )),
+    }
 }
 
 fn parse(
     mut input: syn::ItemFn,
-    _args: syn::AttributeArgs,
+    config: Configuration,
     is_test: bool,
 ) -> Result<TokenStream, syn::Error> {
     if input.sig.asyncness.take().is_none() {
@@ -71,46 +253,294 @@ fn parse(
 
     let body = &input.block;
     let brace_token = input.block.brace_token;
+    let (default_seed, time_imports) = match config.seed {
+        Some(seed) => (quote! { #seed }, quote! { use std::time::Duration; }),
+        None => (
+            quote! {
+                SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs()
+            },
+            quote! { use std::time::{Duration, SystemTime}; },
+        ),
+    };
+    let default_repeat = config.repeat.unwrap_or(1);
+    let default_time_limit = match config.time_limit {
+        Some(limit) => quote! { Some(#limit) },
+        None => quote! { None },
+    };
+    let default_check = config.check;
+    let default_jobs = config.jobs.unwrap_or(1);
+    let should_panic = config.should_panic;
+    let expected_panic_message = match &config.expected_panic_message {
+        Some(msg) => quote! { Some(#msg) },
+        None => quote! { None },
+    };
+    let crate_name = &config.crate_name;
+
+    // The artifact write/load helpers (and the `MADSIM_TEST_REPLAY` fast path below) are
+    // the only pieces of the expansion that need `#crate_name::export::{serde, serde_json}`
+    // and a `Serialize + DeserializeOwned` rand log type. They're only emitted when
+    // madsim-macros' own `fail-artifacts` feature is enabled, so crates that don't opt in
+    // never pick up that `serde`/`serde_json` requirement.
+    let artifact_fns: TokenStream2 = if cfg!(feature = "fail-artifacts") {
+        quote! {
+            fn __madsim_write_artifact<L: #crate_name::export::serde::Serialize>(dir: &str, seed: u64, rand_log: &L) {
+                let path = std::path::Path::new(dir).join(format!("madsim-seed-{}.json", seed));
+                match std::fs::File::create(&path).map(|f| #crate_name::export::serde_json::to_writer(f, &(seed, rand_log))) {
+                    Ok(Ok(())) => println!("MADSIM_TEST_ARTIFACT={}", path.display()),
+                    _ => println!("failed to write madsim failure artifact to {}", path.display()),
+                }
+            }
+            fn __madsim_load_artifact<L: #crate_name::export::serde::de::DeserializeOwned>(path: &str) -> (u64, L) {
+                let file = std::fs::File::open(path)
+                    .unwrap_or_else(|e| panic!("failed to open MADSIM_TEST_REPLAY artifact {}: {}", path, e));
+                #crate_name::export::serde_json::from_reader(file)
+                    .unwrap_or_else(|e| panic!("failed to deserialize MADSIM_TEST_REPLAY artifact {}: {}", path, e))
+            }
+        }
+    } else {
+        quote! {}
+    };
+    let replay_block: TokenStream2 = if cfg!(feature = "fail-artifacts") {
+        quote! {
+            if let Ok(replay_path) = std::env::var("MADSIM_TEST_REPLAY") {
+                let (seed, rand_log) = __madsim_load_artifact(&replay_path);
+                let mut rt = #crate_name::Runtime::new_with_seed(seed);
+                rt.enable_deterministic_check(Some(rand_log));
+                if let Some(limit) = time_limit_s {
+                    rt.set_time_limit(Duration::from_secs_f64(limit));
+                }
+                rt.block_on(async #body);
+                return;
+            }
+        }
+    } else {
+        quote! {}
+    };
+    // Emits `if let (Ok(dir), Some(log)) = (..., &#rand_log_expr) { __madsim_write_artifact(...) }`,
+    // or nothing when `fail-artifacts` is disabled -- keeping the artifact write itself
+    // behind the same feature gate as the helper functions it calls.
+    let write_artifact_stmt = |rand_log_expr: TokenStream2| -> TokenStream2 {
+        if cfg!(feature = "fail-artifacts") {
+            quote! {
+                if let (Ok(dir), Some(log)) = (std::env::var("MADSIM_TEST_ARTIFACT_DIR"), &#rand_log_expr) {
+                    __madsim_write_artifact(&dir, seed, log);
+                }
+            }
+        } else {
+            quote! {}
+        }
+    };
+    let write_artifact_this_rand_log = write_artifact_stmt(quote! { this_rand_log });
+    let write_artifact_parallel = write_artifact_stmt(quote! { rt.into_inner().take_rand_log() });
+
     input.block = syn::parse2(quote! {
         {
-            use std::time::{Duration, SystemTime};
+            #time_imports
+            fn __madsim_panic_matches(e: &(dyn std::any::Any + Send), expected: Option<&str>) -> bool {
+                match expected {
+                    None => true,
+                    Some(expected) => e
+                        .downcast_ref::<String>()
+                        .map(|s| s.as_str())
+                        .or_else(|| e.downcast_ref::<&str>().copied())
+                        .map(|s| s.contains(expected))
+                        .unwrap_or(false),
+                }
+            }
+            ::std::thread_local! {
+                static __MADSIM_QUIET_PANIC: std::cell::Cell<bool> = std::cell::Cell::new(false);
+            }
+            // The panic hook is process-global, and `cargo test` runs test functions
+            // concurrently, so naively swapping it in and out around the sweep would race
+            // with sibling tests (including other `#[madsim::test(jobs = ...)]` sweeps) and
+            // can leave a permanently-silent hook installed. Install a hook that defers to
+            // the previous one at most once per annotated function (the `Once` belongs to
+            // this expansion, so hooks of different tests chain), and have each worker thread mute
+            // only its own panics via a thread-local flag, leaving concurrently-running
+            // tests' panic output untouched.
+            fn __madsim_install_quiet_panic_hook() {
+                static INIT: std::sync::Once = std::sync::Once::new();
+                INIT.call_once(|| {
+                    let default_hook = std::panic::take_hook();
+                    std::panic::set_hook(Box::new(move |info| {
+                        if !__MADSIM_QUIET_PANIC.with(|quiet| quiet.get()) {
+                            default_hook(info);
+                        }
+                    }));
+                });
+            }
+            #artifact_fns
+            let should_panic: bool = #should_panic;
+            let expected_panic_message: Option<&str> = #expected_panic_message;
             let seed: u64 = if let Ok(seed_str) = std::env::var("MADSIM_TEST_SEED") {
                 seed_str.parse().expect("MADSIM_TEST_SEED should be an integer")
             } else {
-                SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_secs()
+                #default_seed
             };
             let mut count: u64 = if let Ok(num_str) = std::env::var("MADSIM_TEST_NUM") {
                 num_str.parse().expect("MADSIM_TEST_NUM should be an integer")
             } else {
-                1
+                #default_repeat
             };
-            let time_limit_s = std::env::var("MADSIM_TEST_TIME_LIMIT").ok().map(|num_str| {
-                num_str.parse::<f64>().expect("MADSIM_TEST_TIME_LIMIT should be an number")
-            });
-            let check = std::env::var("MADSIM_TEST_CHECK_DETERMINISTIC").is_ok();
+            let time_limit_s = if let Ok(num_str) = std::env::var("MADSIM_TEST_TIME_LIMIT") {
+                Some(num_str.parse::<f64>().expect("MADSIM_TEST_TIME_LIMIT should be an number"))
+            } else {
+                #default_time_limit
+            };
+            #replay_block
+            let check = std::env::var("MADSIM_TEST_CHECK_DETERMINISTIC").is_ok() || #default_check;
             if check {
                 count = 2;
             }
-            let mut rand_log = None;
-            for i in 0..count {
-                let seed = if check { seed } else { seed + i };
-                let rand_log0 = rand_log.take();
-                let ret = std::panic::catch_unwind(move || {
-                    let mut rt = madsim::Runtime::new_with_seed(seed);
-                    if check {
-                        rt.enable_deterministic_check(rand_log0);
+            let jobs: u64 = if let Ok(jobs_str) = std::env::var("MADSIM_TEST_JOBS") {
+                jobs_str.parse().expect("MADSIM_TEST_JOBS should be an integer")
+            } else {
+                #default_jobs
+            };
+            // The determinism check compares consecutive `rand_log`s, so it must stay
+            // single-threaded and sequential regardless of the requested job count.
+            let jobs = if check { 1 } else { jobs.max(1) };
+            // Running several `#crate_name::Runtime`s concurrently on separate OS threads
+            // is only exercised once the consuming crate opts into madsim-macros'
+            // `parallel-seeds` feature: that's a deliberate acknowledgement that its
+            // `Runtime` is safe to construct and drive off the main thread alongside
+            // sibling runtimes, which this crate has no way to confirm on its own.
+            // Without the feature, `MADSIM_TEST_JOBS`/`jobs = ...` are accepted but ignored
+            // and the sweep stays sequential.
+            let jobs = if cfg!(feature = "parallel-seeds") { jobs } else { 1 };
+
+            if jobs <= 1 {
+                let mut rand_log = None;
+                let mut panicked_ok = false;
+                for i in 0..count {
+                    let seed = if check { seed } else { seed + i };
+                    let rand_log0 = rand_log.take();
+                    let rt = std::cell::RefCell::new({
+                        let mut rt = #crate_name::Runtime::new_with_seed(seed);
+                        if check {
+                            rt.enable_deterministic_check(rand_log0);
+                        }
+                        if let Some(limit) = time_limit_s {
+                            rt.set_time_limit(Duration::from_secs_f64(limit));
+                        }
+                        rt
+                    });
+                    let ret = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                        rt.borrow_mut().block_on(async #body);
+                    }));
+                    let this_rand_log = rt.into_inner().take_rand_log();
+                    match ret {
+                        Err(e) => {
+                            if should_panic {
+                                if __madsim_panic_matches(&*e, expected_panic_message) {
+                                    panicked_ok = true;
+                                    rand_log = None;
+                                    continue;
+                                }
+                                if panicked_ok {
+                                    // An earlier seed already satisfied `should_panic`;
+                                    // don't fail the test over a later, unrelated panic.
+                                    rand_log = None;
+                                    continue;
+                                }
+                                #write_artifact_this_rand_log
+                                panic!(
+                                    "test panicked at seed {}, but the panic message did not contain the expected substring `{}`",
+                                    seed,
+                                    expected_panic_message.unwrap_or_default(),
+                                );
+                            }
+                            #write_artifact_this_rand_log
+                            println!("MADSIM_TEST_SEED={}", seed);
+                            std::panic::resume_unwind(e);
+                        }
+                        Ok(()) => rand_log = this_rand_log,
                     }
-                    if let Some(limit) = time_limit_s {
-                        rt.set_time_limit(Duration::from_secs_f64(limit));
+                }
+                if should_panic && !panicked_ok {
+                    panic!("test did not panic as expected across {} seed(s)", count);
+                }
+            } else {
+                let failure = std::sync::Arc::new(std::sync::Mutex::new(None));
+                let panicked_ok = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+                __madsim_install_quiet_panic_hook();
+                let handles: Vec<_> = (0..jobs)
+                    .map(|job| {
+                        let failure = failure.clone();
+                        let panicked_ok = panicked_ok.clone();
+                        std::thread::spawn(move || {
+                            let mut i = job;
+                            while i < count {
+                                let seed = seed + i;
+                                let rt = std::cell::RefCell::new({
+                                    let mut rt = #crate_name::Runtime::new_with_seed(seed);
+                                    if let Some(limit) = time_limit_s {
+                                        rt.set_time_limit(Duration::from_secs_f64(limit));
+                                    }
+                                    rt
+                                });
+                                __MADSIM_QUIET_PANIC.with(|quiet| quiet.set(true));
+                                let ret = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                                    rt.borrow_mut().block_on(async #body);
+                                }));
+                                __MADSIM_QUIET_PANIC.with(|quiet| quiet.set(false));
+                                if let Err(e) = ret {
+                                    if should_panic {
+                                        if __madsim_panic_matches(&*e, expected_panic_message) {
+                                            panicked_ok.store(true, std::sync::atomic::Ordering::SeqCst);
+                                            // This worker's job is done: some seed already
+                                            // satisfied `should_panic`, so stop sweeping.
+                                            return;
+                                        }
+                                        if panicked_ok.load(std::sync::atomic::Ordering::SeqCst) {
+                                            // Another (or this) worker already found a
+                                            // matching panic; ignore this unrelated one.
+                                            return;
+                                        }
+                                        #write_artifact_parallel
+                                        let msg = format!(
+                                            "test panicked at seed {}, but the panic message did not contain the expected substring `{}`",
+                                            seed,
+                                            expected_panic_message.unwrap_or_default(),
+                                        );
+                                        let mut failure = failure.lock().unwrap();
+                                        if failure.is_none() {
+                                            *failure = Some((
+                                                seed,
+                                                Box::new(msg) as Box<dyn std::any::Any + Send>,
+                                            ));
+                                        }
+                                        return;
+                                    }
+                                    #write_artifact_parallel
+                                    let mut failure = failure.lock().unwrap();
+                                    if failure.is_none() {
+                                        *failure = Some((seed, e));
+                                    }
+                                    return;
+                                }
+                                i += jobs;
+                            }
+                        })
+                    })
+                    .collect();
+                for handle in handles {
+                    let _ = handle.join();
+                }
+                if should_panic {
+                    if panicked_ok.load(std::sync::atomic::Ordering::SeqCst) {
+                        // Already satisfied; ignore any mismatched-panic failure that a
+                        // concurrently running worker may have recorded in the meantime.
+                    } else if let Some((seed, e)) = failure.lock().unwrap().take() {
+                        println!("MADSIM_TEST_SEED={}", seed);
+                        std::panic::resume_unwind(e);
+                    } else {
+                        panic!("test did not panic as expected across {} seed(s)", count);
                     }
-                    rt.block_on(async #body);
-                    rt.take_rand_log()
-                });
-                if let Err(e) = ret {
+                } else if let Some((seed, e)) = failure.lock().unwrap().take() {
                     println!("MADSIM_TEST_SEED={}", seed);
                     std::panic::resume_unwind(e);
                 }
-                rand_log = ret.unwrap();
             }
         }
     })